@@ -0,0 +1,267 @@
+//! A single open perf counter.
+
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use libc::c_int;
+
+use error::PerfEventsError;
+use events::Event;
+use raw::{attr_flag_bits, perf_event_attr, PERF_FORMAT_TOTAL_TIME_ENABLED, PERF_FORMAT_TOTAL_TIME_RUNNING};
+use sys::{self, OpenError};
+use {CpuConfig, PidConfig};
+
+/// A counter value as reported by the kernel, along with the enabled/running
+/// times needed to tell whether it was scaled up to account for PMU
+/// multiplexing (see [`Reading::scale_factor`]).
+#[derive(Clone, Copy, Debug)]
+pub struct Reading {
+    /// The count the kernel returned, scaled up by
+    /// `time_enabled / time_running` when the event was multiplexed.
+    pub value: u64,
+    /// Total time the event was enabled for, in nanoseconds.
+    pub time_enabled: u64,
+    /// Total time the event was actually on a PMU, in nanoseconds. Less
+    /// than `time_enabled` when the kernel had to time-share the PMU
+    /// between more events than it has hardware slots.
+    pub time_running: u64,
+}
+
+impl Reading {
+    /// The factor `value` was scaled up by: `time_enabled / time_running`.
+    /// `1.0` means it was never multiplexed away; values well above `1.0`
+    /// mean `value` is a rough estimate rather than an exact count. `0.0`
+    /// if the event never ran at all.
+    pub fn scale_factor(&self) -> f64 {
+        if self.time_running == 0 {
+            0.0
+        } else {
+            self.time_enabled as f64 / self.time_running as f64
+        }
+    }
+}
+
+/// One open `perf_event_open(2)` file descriptor, counting a single
+/// [`Event`] for a single (pid, cpu) pair.
+#[derive(Debug)]
+pub struct EventCounter {
+    event: Event,
+    fd: RawFd,
+    read_format: u64,
+}
+
+impl EventCounter {
+    pub fn new(event: Event, pid: PidConfig, cpu: CpuConfig) -> Result<Self, OpenError> {
+        let read_format = PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING;
+        Self::with_group(event, pid, cpu, -1, read_format, true)
+    }
+
+    /// As [`Self::new`], but join an existing group leader's fd and open
+    /// with the given `read_format` (e.g. `PERF_FORMAT_GROUP`). Pass
+    /// `group_fd: -1` to create a new, independent leader.
+    ///
+    /// `inherit` must be `false` when `read_format` includes
+    /// `PERF_FORMAT_GROUP`: the kernel rejects `inherit` combined with
+    /// `PERF_FORMAT_GROUP` with `EINVAL`, so [`::group::CounterGroup`]
+    /// opens its members with `inherit: false`.
+    pub(crate) fn with_group(
+        event: Event,
+        pid: PidConfig,
+        cpu: CpuConfig,
+        group_fd: c_int,
+        read_format: u64,
+        inherit: bool,
+    ) -> Result<Self, OpenError> {
+        let mut attr = perf_event_attr::default();
+        let (type_, config) = event.type_and_config();
+        attr.type_ = type_;
+        attr.config = config;
+        attr.size = mem::size_of::<perf_event_attr>() as u32;
+        attr.read_format = read_format;
+        attr.set_flag(attr_flag_bits::DISABLED, group_fd < 0);
+        attr.set_flag(attr_flag_bits::INHERIT, inherit);
+
+        let fd = sys::perf_event_open(&attr, pid.raw(), cpu.raw(), group_fd, 0)?;
+
+        Ok(EventCounter {
+            event,
+            fd,
+            read_format,
+        })
+    }
+
+    /// Open a counter that fires a kernel overflow notification every
+    /// `threshold` occurrences of `event`, for [`::monitor::Monitor`].
+    pub(crate) fn with_period(
+        event: Event,
+        pid: PidConfig,
+        cpu: CpuConfig,
+        threshold: u64,
+    ) -> Result<Self, OpenError> {
+        let mut attr = perf_event_attr::default();
+        let (type_, config) = event.type_and_config();
+        attr.type_ = type_;
+        attr.config = config;
+        attr.size = mem::size_of::<perf_event_attr>() as u32;
+        attr.sample_period_or_freq = threshold;
+        attr.wakeup_events_or_watermark = 1;
+        attr.set_flag(attr_flag_bits::DISABLED, true);
+
+        let fd = sys::perf_event_open(&attr, pid.raw(), cpu.raw(), -1, 0)?;
+
+        Ok(EventCounter {
+            event,
+            fd,
+            read_format: 0,
+        })
+    }
+
+    pub fn event(&self) -> Event {
+        self.event
+    }
+
+    pub fn enable(&self) -> Result<(), PerfEventsError> {
+        unsafe { sys::enable(self.fd, 0) }
+            .map(|_| ())
+            .map_err(|inner| PerfEventsError::IoctlError { inner })
+    }
+
+    pub fn disable(&self) -> Result<(), PerfEventsError> {
+        unsafe { sys::disable(self.fd, 0) }
+            .map(|_| ())
+            .map_err(|inner| PerfEventsError::IoctlError { inner })
+    }
+
+    pub fn read(&self) -> Result<(Event, Reading), PerfEventsError> {
+        let wants_total_time = self.read_format
+            & (PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING)
+            != 0;
+
+        let mut buf = [0u8; 24];
+        let len = if wants_total_time { 24 } else { 8 };
+        self.read_fd(&mut buf[..len])
+            .map_err(|inner| PerfEventsError::ReadError { inner })?;
+
+        Ok((self.event, parse_reading(&buf, wants_total_time)))
+    }
+
+    pub(crate) fn read_fd(&self, buf: &mut [u8]) -> io::Result<()> {
+        let mut file = unsafe { fs_from_fd(self.fd) };
+        let result = file.read_exact(buf);
+        mem::forget(file); // we don't own the fd, don't close it on drop
+        result
+    }
+}
+
+impl AsRawFd for EventCounter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for EventCounter {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Borrow `fd` as a `File` without taking ownership of it; the caller is
+/// responsible for making sure the fd outlives the borrow (and for not
+/// letting the returned `File` close it).
+unsafe fn fs_from_fd(fd: RawFd) -> ::std::fs::File {
+    use std::os::unix::io::FromRawFd;
+    ::std::fs::File::from_raw_fd(fd)
+}
+
+/// Decode a `read(2)` result off a perf fd into a [`Reading`], scaling
+/// `value` by `time_enabled / time_running` when the event was
+/// multiplexed. `buf` holds the first 8 bytes (`value`) when
+/// `wants_total_time` is `false`, or the full 24 bytes (`value`,
+/// `time_enabled`, `time_running`) when it's `true`.
+fn parse_reading(buf: &[u8; 24], wants_total_time: bool) -> Reading {
+    let value = u64::from_ne_bytes([
+        buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7],
+    ]);
+    let (time_enabled, time_running) = if wants_total_time {
+        (
+            u64::from_ne_bytes([
+                buf[8], buf[9], buf[10], buf[11], buf[12], buf[13], buf[14], buf[15],
+            ]),
+            u64::from_ne_bytes([
+                buf[16], buf[17], buf[18], buf[19], buf[20], buf[21], buf[22], buf[23],
+            ]),
+        )
+    } else {
+        (0, 0)
+    };
+
+    let scaled_value = if time_running == 0 || time_enabled == time_running {
+        value
+    } else {
+        ((value as u128 * time_enabled as u128) / time_running as u128) as u64
+    };
+
+    Reading {
+        value: scaled_value,
+        time_enabled,
+        time_running,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reading_buf(value: u64, time_enabled: u64, time_running: u64) -> [u8; 24] {
+        let mut buf = [0u8; 24];
+        buf[0..8].copy_from_slice(&value.to_ne_bytes());
+        buf[8..16].copy_from_slice(&time_enabled.to_ne_bytes());
+        buf[16..24].copy_from_slice(&time_running.to_ne_bytes());
+        buf
+    }
+
+    #[test]
+    fn not_multiplexed_reads_value_unscaled() {
+        let buf = reading_buf(42, 1000, 1000);
+        let reading = parse_reading(&buf, true);
+
+        assert_eq!(reading.value, 42);
+        assert_eq!(reading.time_enabled, 1000);
+        assert_eq!(reading.time_running, 1000);
+        assert_eq!(reading.scale_factor(), 1.0);
+    }
+
+    #[test]
+    fn multiplexed_scales_value_up() {
+        // The PMU only ran the event for half of the enabled time, so the
+        // raw count should be doubled to estimate what it would've been if
+        // it had run the whole time.
+        let buf = reading_buf(50, 1000, 500);
+        let reading = parse_reading(&buf, true);
+
+        assert_eq!(reading.value, 100);
+        assert_eq!(reading.scale_factor(), 2.0);
+    }
+
+    #[test]
+    fn never_running_is_not_scaled_and_has_zero_scale_factor() {
+        let buf = reading_buf(0, 1000, 0);
+        let reading = parse_reading(&buf, true);
+
+        assert_eq!(reading.value, 0);
+        assert_eq!(reading.scale_factor(), 0.0);
+    }
+
+    #[test]
+    fn without_total_time_format_enabled_and_running_are_zero() {
+        let buf = reading_buf(7, 999, 999);
+        let reading = parse_reading(&buf, false);
+
+        assert_eq!(reading.value, 7);
+        assert_eq!(reading.time_enabled, 0);
+        assert_eq!(reading.time_running, 0);
+    }
+}
@@ -0,0 +1,21 @@
+//! Error types shared across the crate's public API.
+
+use std::io;
+
+use nix;
+
+#[derive(Debug, Fail)]
+pub enum PerfEventsError {
+    #[fail(display = "failed to start counters: {}", inner)]
+    StartError { inner: String },
+
+    #[fail(display = "failed to read counter: {}", inner)]
+    ReadError { inner: io::Error },
+
+    #[fail(display = "failed to control counter: {}", inner)]
+    IoctlError { inner: nix::Error },
+}
+
+/// Alias kept for the record-parsing code, which predates the
+/// `PerfEventsError` name.
+pub type Error = PerfEventsError;
@@ -0,0 +1,49 @@
+//! The events this crate knows how to ask the kernel to count.
+
+use raw::{PERF_TYPE_HARDWARE, PERF_TYPE_SOFTWARE};
+
+/// A countable/sampleable perf event.
+///
+/// Each variant maps to a `(type, config)` pair as understood by
+/// `perf_event_open(2)`; see `Event::type_and_config`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, EnumIter)]
+pub enum Event {
+    CpuCycles,
+    Instructions,
+    CacheReferences,
+    CacheMisses,
+    BranchInstructions,
+    BranchMisses,
+    BusCycles,
+    CpuClock,
+    TaskClock,
+    PageFaults,
+    ContextSwitches,
+    CpuMigrations,
+}
+
+impl Event {
+    /// Every event this crate supports, in the order `all_available()`
+    /// tries them.
+    pub fn all_events() -> Vec<Event> {
+        use strum::IntoEnumIterator;
+        Event::iter().collect()
+    }
+
+    pub(crate) fn type_and_config(&self) -> (u32, u64) {
+        match *self {
+            Event::CpuCycles => (PERF_TYPE_HARDWARE, 0),
+            Event::Instructions => (PERF_TYPE_HARDWARE, 1),
+            Event::CacheReferences => (PERF_TYPE_HARDWARE, 2),
+            Event::CacheMisses => (PERF_TYPE_HARDWARE, 3),
+            Event::BranchInstructions => (PERF_TYPE_HARDWARE, 4),
+            Event::BranchMisses => (PERF_TYPE_HARDWARE, 5),
+            Event::BusCycles => (PERF_TYPE_HARDWARE, 6),
+            Event::CpuClock => (PERF_TYPE_SOFTWARE, 0),
+            Event::TaskClock => (PERF_TYPE_SOFTWARE, 1),
+            Event::PageFaults => (PERF_TYPE_SOFTWARE, 2),
+            Event::ContextSwitches => (PERF_TYPE_SOFTWARE, 3),
+            Event::CpuMigrations => (PERF_TYPE_SOFTWARE, 4),
+        }
+    }
+}
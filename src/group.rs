@@ -0,0 +1,95 @@
+//! Groups of counters read atomically off a single group leader, so that
+//! e.g. an IPC ratio is computed from two values sampled at the same
+//! instant rather than from two independent, independently-multiplexed
+//! reads.
+
+use std::os::unix::io::AsRawFd;
+
+use events::Event;
+use counter::EventCounter;
+use error::PerfEventsError;
+use raw::PERF_FORMAT_GROUP;
+use sys::{self, PERF_IOC_FLAG_GROUP};
+use {CpuConfig, PidConfig};
+
+/// A set of counters opened together, so the kernel can schedule and read
+/// them as one unit. `counters[0]` is the group leader.
+pub struct CounterGroup {
+    counters: Vec<EventCounter>,
+}
+
+impl CounterGroup {
+    pub(crate) fn open(
+        events: &[Event],
+        pid: PidConfig,
+        cpu: CpuConfig,
+    ) -> Result<Self, PerfEventsError> {
+        let mut counters = Vec::with_capacity(events.len());
+        let mut leader_fd = -1;
+
+        for &event in events {
+            let counter =
+                EventCounter::with_group(event, pid, cpu, leader_fd, PERF_FORMAT_GROUP, false)
+                    .map_err(|why| PerfEventsError::StartError {
+                        inner: format!("{}", why),
+                    })?;
+
+            if leader_fd < 0 {
+                leader_fd = counter.as_raw_fd();
+            }
+
+            counters.push(counter);
+        }
+
+        Ok(CounterGroup { counters })
+    }
+
+    fn leader(&self) -> &EventCounter {
+        &self.counters[0]
+    }
+
+    /// Enable every member of the group at the same instant.
+    pub fn enable(&self) -> Result<(), PerfEventsError> {
+        unsafe { sys::enable(self.leader().as_raw_fd(), PERF_IOC_FLAG_GROUP) }
+            .map(|_| ())
+            .map_err(|inner| PerfEventsError::IoctlError { inner })
+    }
+
+    /// Disable every member of the group at the same instant.
+    pub fn disable(&self) -> Result<(), PerfEventsError> {
+        unsafe { sys::disable(self.leader().as_raw_fd(), PERF_IOC_FLAG_GROUP) }
+            .map(|_| ())
+            .map_err(|inner| PerfEventsError::IoctlError { inner })
+    }
+
+    /// Read every member's value as sampled at the same instant, in the
+    /// order the group was created.
+    pub fn read(&self) -> Result<Vec<(Event, u64)>, PerfEventsError> {
+        // PERF_FORMAT_GROUP layout: { u64 nr; u64 values[nr]; }
+        //
+        // A perf fd re-serializes the whole read_format from the start on
+        // every read(2) (it ignores the file position), so `nr` and the
+        // values have to come out of a single read() into one buffer --
+        // two separate reads would just return the header twice and shift
+        // every value down by one slot.
+        let mut buf = vec![0u8; (1 + self.counters.len()) * 8];
+        self.leader()
+            .read_fd(&mut buf)
+            .map_err(|inner| PerfEventsError::ReadError { inner })?;
+
+        let mut nr_bytes = [0u8; 8];
+        nr_bytes.copy_from_slice(&buf[..8]);
+        let nr = u64::from_ne_bytes(nr_bytes) as usize;
+
+        Ok(self
+            .counters
+            .iter()
+            .zip(buf[8..8 + nr * 8].chunks_exact(8))
+            .map(|(counter, raw)| {
+                let mut value = [0u8; 8];
+                value.copy_from_slice(raw);
+                (counter.event(), u64::from_ne_bytes(value))
+            })
+            .collect())
+    }
+}
@@ -1,3 +1,4 @@
+extern crate bytes;
 extern crate failure;
 #[macro_use]
 extern crate failure_derive;
@@ -6,9 +7,12 @@ extern crate libc;
 extern crate log;
 #[macro_use]
 extern crate nix;
+#[macro_use]
+extern crate num;
 extern crate strum;
 #[macro_use]
 extern crate strum_macros;
+extern crate tokio_codec;
 
 #[cfg(test)]
 extern crate env_logger;
@@ -16,7 +20,11 @@ extern crate env_logger;
 pub(crate) mod counter;
 pub mod error;
 pub mod events;
+pub(crate) mod group;
+pub mod monitor;
+pub(crate) mod process;
 pub(crate) mod raw;
+pub mod sample;
 pub(crate) mod sys;
 
 use std::collections::{BTreeMap, BTreeSet};
@@ -24,8 +32,10 @@ use std::collections::{BTreeMap, BTreeSet};
 use libc::{c_int, pid_t};
 
 use counter::EventCounter;
+pub use counter::Reading;
 pub use error::PerfEventsError;
 use events::Event;
+pub use group::CounterGroup;
 
 pub struct Counts {
     counters: Vec<EventCounter>,
@@ -37,6 +47,7 @@ impl Counts {
             pid,
             cpu,
             to_count: BTreeSet::new(),
+            freeze: false,
         }
     }
 
@@ -44,17 +55,37 @@ impl Counts {
         self.counters.iter().map(|c| c.enable()).collect()
     }
 
-    pub fn read(&mut self) -> Vec<(Event, u64)> {
-        self.counters
-            .iter_mut()
-            .filter_map(|c| {
-                let res = c.read();
-                if let Err(ref why) = res {
+    /// Read every counter's scaled value, along with the enabled/running
+    /// times needed to tell how much (if any) PMU multiplexing occurred;
+    /// see [`Reading::scale_factor`].
+    ///
+    /// When a [`PidConfig::Process`] fanned an event out across multiple
+    /// threads, its per-thread counters are summed back into a single
+    /// reading here.
+    pub fn read(&mut self) -> Vec<(Event, Reading)> {
+        let mut totals: BTreeMap<Event, Reading> = BTreeMap::new();
+
+        for c in &mut self.counters {
+            let res = c.read();
+            let (event, reading) = match res {
+                Ok(pair) => pair,
+                Err(ref why) => {
                     debug!("error reading counter: {}", why);
+                    continue;
                 }
-                res.ok()
-            })
-            .collect()
+            };
+
+            totals
+                .entry(event)
+                .and_modify(|acc| {
+                    acc.value += reading.value;
+                    acc.time_enabled += reading.time_enabled;
+                    acc.time_running += reading.time_running;
+                })
+                .or_insert(reading);
+        }
+
+        totals.into_iter().collect()
     }
 
     pub fn start_all_available() -> Result<Self, PerfEventsError> {
@@ -85,6 +116,7 @@ pub struct CountsBuilder {
     pid: PidConfig,
     cpu: CpuConfig,
     to_count: BTreeSet<Event>,
+    freeze: bool,
 }
 
 impl CountsBuilder {
@@ -101,6 +133,14 @@ impl CountsBuilder {
         self
     }
 
+    /// When `pid` is [`PidConfig::Process`], `SIGSTOP` the target for the
+    /// duration of `create()` (restoring it afterwards, even on error) so
+    /// its thread list can't change out from under the fan-out below.
+    pub fn freeze(mut self) -> Self {
+        self.freeze = true;
+        self
+    }
+
     pub fn create(
         self,
     ) -> (
@@ -110,13 +150,51 @@ impl CountsBuilder {
         let mut counters = Vec::new();
         let mut failures = BTreeMap::new();
 
-        for event in self.to_count {
-            match EventCounter::new(event, self.pid, self.cpu) {
-                Ok(c) => counters.push(c),
+        let _freeze_guard = if self.freeze {
+            match self.pid {
+                PidConfig::Process(pid) => match process::FreezeGuard::new(pid) {
+                    Ok(guard) => Some(guard),
+                    Err(why) => {
+                        debug!("failed to freeze pid {} before counting: {}", pid, why);
+                        None
+                    }
+                },
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let targets: Vec<PidConfig> = match self.pid {
+            PidConfig::Process(pid) => match process::enumerate_tasks(pid) {
+                Ok(tids) => tids.into_iter().map(PidConfig::Other).collect(),
                 Err(why) => {
+                    debug!("failed to enumerate tasks of pid {}: {}", pid, why);
+                    Vec::new()
+                }
+            },
+            other => vec![other],
+        };
+
+        for event in self.to_count {
+            let mut opened_any = false;
+            let mut last_failure = None;
+
+            for &target in &targets {
+                match EventCounter::new(event, target, self.cpu) {
+                    Ok(c) => {
+                        counters.push(c);
+                        opened_any = true;
+                    }
+                    Err(why) => last_failure = Some(why),
+                }
+            }
+
+            if !opened_any {
+                if let Some(why) = last_failure {
                     failures.insert(event, why);
                 }
-            };
+            }
         }
 
         let ret_counts = if counters.len() == 0 {
@@ -133,12 +211,30 @@ impl CountsBuilder {
 
         (ret_counts, ret_failures)
     }
+
+    /// Open `events` as a single group, read atomically off a shared group
+    /// leader instead of each getting its own independently-scheduled fd.
+    ///
+    /// This is what makes ratios like IPC or cache-miss-rate meaningful:
+    /// the members are guaranteed to be sampled at the same instant.
+    pub fn group(self, events: &[Event]) -> Result<CounterGroup, PerfEventsError> {
+        CounterGroup::open(events, self.pid, self.cpu)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum PidConfig {
     Current,
     Other(pid_t),
+    /// Every thread of this process. Unlike `Other`, `CountsBuilder::create`
+    /// expands this into one counter per thread (for the `CpuConfig` the
+    /// builder was given) so a multithreaded target is fully measured
+    /// instead of just its main thread. This is per-thread only, not
+    /// per-(thread, cpu): with `CpuConfig::All` that's fine, since each
+    /// thread's single fd already counts across every CPU it runs on; with
+    /// `CpuConfig::Specific`, each thread's counter is restricted to that
+    /// one CPU rather than fanned out across a set of CPUs.
+    Process(pid_t),
 }
 
 impl PidConfig {
@@ -146,6 +242,10 @@ impl PidConfig {
         match *self {
             PidConfig::Current => 0,
             PidConfig::Other(p) => p,
+            // Only meaningful once `CountsBuilder::create` has expanded
+            // this into per-thread `Other`s; falling back to the main
+            // thread here is the same single-task limitation as `Other`.
+            PidConfig::Process(p) => p,
         }
     }
 }
@@ -0,0 +1,168 @@
+//! Event-driven threshold alerts, modeled after pressure-stall-style poll
+//! monitors: register a counter with a threshold and block until the
+//! kernel signals that it's been crossed, instead of synchronously
+//! polling [`::Counts::read`] in a loop.
+
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+
+use libc::c_void;
+use nix::sys::epoll::{
+    epoll_create1, epoll_ctl, epoll_wait, EpollCreateFlags, EpollEvent, EpollFlags, EpollOp,
+};
+
+use counter::{EventCounter, Reading};
+use error::PerfEventsError;
+use events::Event;
+use raw;
+use sys;
+use {CpuConfig, PidConfig};
+
+/// Identifies a trigger registered with a [`Monitor`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct TriggerId(u64);
+
+struct Trigger {
+    id: TriggerId,
+    counter: EventCounter,
+    /// One mmap'd page so the kernel has somewhere to deliver wakeups to;
+    /// we don't read sample data out of it here, see `sample::Samples`
+    /// for that.
+    mmap_base: *mut c_void,
+}
+
+impl Drop for Trigger {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.mmap_base, raw::page_size() as usize);
+        }
+    }
+}
+
+/// Blocks until one or more registered counters cross their threshold.
+pub struct Monitor {
+    epoll_fd: RawFd,
+    triggers: Vec<Trigger>,
+    next_id: u64,
+}
+
+impl Monitor {
+    pub fn new() -> Result<Self, PerfEventsError> {
+        let epoll_fd = epoll_create1(EpollCreateFlags::empty()).map_err(|why| {
+            PerfEventsError::StartError {
+                inner: format!("epoll_create1 failed: {}", why),
+            }
+        })?;
+
+        Ok(Monitor {
+            epoll_fd,
+            triggers: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Notify when `event` (counted for `pid` on `cpu`) crosses `threshold`
+    /// occurrences.
+    pub fn add_trigger(
+        &mut self,
+        event: Event,
+        pid: PidConfig,
+        cpu: CpuConfig,
+        threshold: u64,
+    ) -> Result<TriggerId, PerfEventsError> {
+        let counter =
+            EventCounter::with_period(event, pid, cpu, threshold).map_err(|why| {
+                PerfEventsError::StartError {
+                    inner: format!("{}", why),
+                }
+            })?;
+
+        let page_size = raw::page_size() as usize;
+        let mmap_base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                counter.as_raw_fd(),
+                0,
+            )
+        };
+        if mmap_base == libc::MAP_FAILED {
+            return Err(PerfEventsError::StartError {
+                inner: "mmap of trigger control page failed".to_string(),
+            });
+        }
+
+        unsafe { sys::refresh(counter.as_raw_fd(), 1) }
+            .map_err(|inner| PerfEventsError::IoctlError { inner })?;
+        counter.enable()?;
+
+        let id = TriggerId(self.next_id);
+        self.next_id += 1;
+
+        let mut epoll_event = EpollEvent::new(EpollFlags::EPOLLIN, id.0);
+        epoll_ctl(
+            self.epoll_fd,
+            EpollOp::EpollCtlAdd,
+            counter.as_raw_fd(),
+            Some(&mut epoll_event),
+        )
+        .map_err(|why| PerfEventsError::StartError {
+            inner: format!("epoll_ctl failed: {}", why),
+        })?;
+
+        self.triggers.push(Trigger {
+            id,
+            counter,
+            mmap_base,
+        });
+
+        Ok(id)
+    }
+
+    /// Block until at least one trigger fires.
+    pub fn wait(&mut self) -> Result<Vec<(TriggerId, Event, Reading)>, PerfEventsError> {
+        self.wait_timeout(None)
+    }
+
+    /// As [`Self::wait`], but give up after `timeout` with an empty result
+    /// if nothing fired.
+    pub fn wait_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<(TriggerId, Event, Reading)>, PerfEventsError> {
+        let timeout_ms = timeout.map(|d| d.as_millis() as isize).unwrap_or(-1);
+
+        let mut epoll_events = vec![EpollEvent::empty(); self.triggers.len().max(1)];
+        let n = epoll_wait(self.epoll_fd, &mut epoll_events, timeout_ms).map_err(|why| {
+            PerfEventsError::StartError {
+                inner: format!("epoll_wait failed: {}", why),
+            }
+        })?;
+
+        let mut fired = Vec::new();
+        for epoll_event in &epoll_events[..n] {
+            let id = TriggerId(epoll_event.data());
+            if let Some(trigger) = self.triggers.iter().find(|t| t.id == id) {
+                let (event, reading) = trigger.counter.read()?;
+                // Re-arm: one-shot overflow notification needs a fresh
+                // PERF_EVENT_IOC_REFRESH after each delivery.
+                unsafe { sys::refresh(trigger.counter.as_raw_fd(), 1) }
+                    .map_err(|inner| PerfEventsError::IoctlError { inner })?;
+                fired.push((id, event, reading));
+            }
+        }
+
+        Ok(fired)
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.epoll_fd);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Attaching to every thread of a process.
+//!
+//! `perf_event_open` only counts the single task ID it's given, so a plain
+//! `PidConfig::Other(pid)` leaves the other threads of a multithreaded
+//! target mostly unmeasured. [`enumerate_tasks`] lists every thread so a
+//! caller can open one counter per thread instead, and [`FreezeGuard`]
+//! optionally pauses the target while that enumeration happens so
+//! short-lived threads can't slip in or out unnoticed.
+
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc::{pid_t, SIGCONT, SIGSTOP};
+
+/// How long [`FreezeGuard::new`] polls `/proc/<pid>/stat` for the target to
+/// actually reach the stopped state before giving up.
+const FREEZE_TIMEOUT: Duration = Duration::from_millis(500);
+const FREEZE_POLL_INTERVAL: Duration = Duration::from_micros(500);
+
+/// List every task (thread) ID currently in process `pid`, by reading
+/// `/proc/<pid>/task/`.
+pub fn enumerate_tasks(pid: pid_t) -> io::Result<Vec<pid_t>> {
+    let mut tasks = Vec::new();
+
+    for entry in fs::read_dir(format!("/proc/{}/task", pid))? {
+        let entry = entry?;
+        if let Some(tid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+            tasks.push(tid);
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// Sends `SIGSTOP` to a process for the lifetime of the guard and
+/// `SIGCONT` when it's dropped -- even if opening counters for it fails
+/// partway through -- so whole-process enumeration doesn't race against
+/// the target's threads exiting or forking.
+pub struct FreezeGuard {
+    pid: pid_t,
+}
+
+impl FreezeGuard {
+    pub fn new(pid: pid_t) -> io::Result<Self> {
+        if unsafe { libc::kill(pid, SIGSTOP) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SIGSTOP delivery is asynchronous: without waiting for the target
+        // to actually reach the stopped state, enumerate_tasks could still
+        // run while threads are exiting or forking -- the exact race this
+        // guard exists to close. The guard is built first so that a failed
+        // wait still sends SIGCONT on drop.
+        let guard = FreezeGuard { pid };
+        wait_until_stopped(pid)?;
+
+        Ok(guard)
+    }
+}
+
+/// Poll `/proc/<pid>/stat` until the task's state is `T` (stopped), up to
+/// [`FREEZE_TIMEOUT`].
+fn wait_until_stopped(pid: pid_t) -> io::Result<()> {
+    let deadline = Instant::now() + FREEZE_TIMEOUT;
+    loop {
+        if task_state(pid)? == 'T' {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("pid {} did not stop within {:?}", pid, FREEZE_TIMEOUT),
+            ));
+        }
+        thread::sleep(FREEZE_POLL_INTERVAL);
+    }
+}
+
+/// The single-character state field (the 3rd, after the parenthesized
+/// comm) of `/proc/<pid>/stat`.
+fn task_state(pid: pid_t) -> io::Result<char> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    let after_comm = stat.rfind(')').ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/<pid>/stat")
+    })?;
+    stat[after_comm + 2..]
+        .chars()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/<pid>/stat"))
+}
+
+impl Drop for FreezeGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::kill(self.pid, SIGCONT);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Raw FFI bindings for the parts of the `perf_event_open(2)` ABI that
+//! aren't already exposed by `libc`.
+//!
+//! These mirror the kernel's `<linux/perf_event.h>` layout byte-for-byte;
+//! field names match the header so they're easy to cross-reference against
+//! the man page.
+
+#![allow(non_camel_case_types)]
+
+use libc::{c_int, c_ulong};
+
+pub const PERF_TYPE_HARDWARE: u32 = 0;
+pub const PERF_TYPE_SOFTWARE: u32 = 1;
+pub const PERF_TYPE_TRACEPOINT: u32 = 2;
+pub const PERF_TYPE_HW_CACHE: u32 = 3;
+pub const PERF_TYPE_RAW: u32 = 4;
+pub const PERF_TYPE_BREAKPOINT: u32 = 5;
+
+// perf_event_attr.sample_type
+pub const PERF_SAMPLE_IP: u64 = 1 << 0;
+pub const PERF_SAMPLE_TID: u64 = 1 << 1;
+pub const PERF_SAMPLE_TIME: u64 = 1 << 2;
+pub const PERF_SAMPLE_ADDR: u64 = 1 << 3;
+pub const PERF_SAMPLE_READ: u64 = 1 << 4;
+pub const PERF_SAMPLE_CALLCHAIN: u64 = 1 << 5;
+pub const PERF_SAMPLE_ID: u64 = 1 << 6;
+pub const PERF_SAMPLE_CPU: u64 = 1 << 7;
+pub const PERF_SAMPLE_PERIOD: u64 = 1 << 8;
+pub const PERF_SAMPLE_STREAM_ID: u64 = 1 << 9;
+pub const PERF_SAMPLE_RAW: u64 = 1 << 10;
+pub const PERF_SAMPLE_BRANCH_STACK: u64 = 1 << 11;
+pub const PERF_SAMPLE_REGS_USER: u64 = 1 << 12;
+pub const PERF_SAMPLE_STACK_USER: u64 = 1 << 13;
+pub const PERF_SAMPLE_WEIGHT: u64 = 1 << 14;
+pub const PERF_SAMPLE_DATA_SRC: u64 = 1 << 15;
+pub const PERF_SAMPLE_IDENTIFIER: u64 = 1 << 16;
+pub const PERF_SAMPLE_TRANSACTION: u64 = 1 << 17;
+pub const PERF_SAMPLE_REGS_INTR: u64 = 1 << 18;
+
+// perf_event_attr.read_format
+pub const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+pub const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+pub const PERF_FORMAT_ID: u64 = 1 << 2;
+pub const PERF_FORMAT_GROUP: u64 = 1 << 3;
+
+// perf_event_attr.branch_sample_type
+pub const PERF_SAMPLE_BRANCH_USER: u64 = 1 << 0;
+pub const PERF_SAMPLE_BRANCH_KERNEL: u64 = 1 << 1;
+pub const PERF_SAMPLE_BRANCH_HV: u64 = 1 << 2;
+pub const PERF_SAMPLE_BRANCH_ANY: u64 = 1 << 3;
+pub const PERF_SAMPLE_BRANCH_ANY_CALL: u64 = 1 << 4;
+pub const PERF_SAMPLE_BRANCH_ANY_RETURN: u64 = 1 << 5;
+pub const PERF_SAMPLE_BRANCH_IND_CALL: u64 = 1 << 6;
+pub const PERF_SAMPLE_BRANCH_ABORT_TX: u64 = 1 << 7;
+pub const PERF_SAMPLE_BRANCH_IN_TX: u64 = 1 << 8;
+pub const PERF_SAMPLE_BRANCH_NO_TX: u64 = 1 << 9;
+pub const PERF_SAMPLE_BRANCH_COND: u64 = 1 << 10;
+pub const PERF_SAMPLE_BRANCH_CALL_STACK: u64 = 1 << 11;
+pub const PERF_SAMPLE_BRANCH_IND_JUMP: u64 = 1 << 12;
+pub const PERF_SAMPLE_BRANCH_CALL: u64 = 1 << 13;
+pub const PERF_SAMPLE_BRANCH_NO_FLAGS: u64 = 1 << 14;
+pub const PERF_SAMPLE_BRANCH_NO_CYCLES: u64 = 1 << 15;
+
+/// Bit positions within `perf_event_attr`'s packed boolean flags.
+///
+/// The kernel header expresses these as C bitfields; since Rust has no
+/// portable equivalent we keep them as a single `u64` (see `attr_flags`
+/// below) and expose these as shift amounts.
+pub mod attr_flag_bits {
+    pub const DISABLED: u8 = 0;
+    pub const INHERIT: u8 = 1;
+    pub const PINNED: u8 = 2;
+    pub const EXCLUSIVE: u8 = 3;
+    pub const EXCLUDE_USER: u8 = 4;
+    pub const EXCLUDE_KERNEL: u8 = 5;
+    pub const EXCLUDE_HV: u8 = 6;
+    pub const EXCLUDE_IDLE: u8 = 7;
+    pub const MMAP: u8 = 8;
+    pub const COMM: u8 = 9;
+    pub const FREQ: u8 = 10;
+    pub const INHERIT_STAT: u8 = 11;
+    pub const ENABLE_ON_EXEC: u8 = 12;
+    pub const TASK: u8 = 13;
+    pub const WATERMARK: u8 = 14;
+    // precise_ip occupies bits 15-16 (2 bits wide).
+    pub const MMAP_DATA: u8 = 17;
+    pub const SAMPLE_ID_ALL: u8 = 18;
+    pub const EXCLUDE_HOST: u8 = 19;
+    pub const EXCLUDE_GUEST: u8 = 20;
+    pub const EXCLUDE_CALLCHAIN_KERNEL: u8 = 21;
+    pub const EXCLUDE_CALLCHAIN_USER: u8 = 22;
+    pub const MMAP2: u8 = 23;
+    pub const COMM_EXEC: u8 = 24;
+    pub const USE_CLOCKID: u8 = 25;
+    pub const CONTEXT_SWITCH: u8 = 26;
+    pub const WRITE_BACKWARD: u8 = 27;
+    pub const NAMESPACES: u8 = 28;
+    pub const KSYMBOL: u8 = 29;
+    pub const BPF_EVENT: u8 = 30;
+    pub const AUX_OUTPUT: u8 = 31;
+    pub const CGROUP: u8 = 32;
+    pub const TEXT_POKE: u8 = 33;
+}
+
+/// `struct perf_event_attr` as passed to `perf_event_open(2)`.
+///
+/// The packed C bitfields (`disabled`, `inherit`, `mmap2`, `context_switch`,
+/// ...) are collapsed into the single `flags` word; use [`Self::set_flag`]
+/// / [`Self::flag`] rather than poking at it directly.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct perf_event_attr {
+    pub type_: u32,
+    pub size: u32,
+    pub config: u64,
+    /// Union of `sample_period` / `sample_freq`.
+    pub sample_period_or_freq: u64,
+    pub sample_type: u64,
+    pub read_format: u64,
+    pub flags: u64,
+    /// Union of `wakeup_events` / `wakeup_watermark`.
+    pub wakeup_events_or_watermark: u32,
+    pub bp_type: u32,
+    /// Union of `bp_addr` / `config1`.
+    pub bp_addr_or_config1: u64,
+    /// Union of `bp_len` / `config2`.
+    pub bp_len_or_config2: u64,
+    pub branch_sample_type: u64,
+    pub sample_regs_user: u64,
+    pub sample_stack_user: u32,
+    pub clockid: c_int,
+    pub sample_regs_intr: u64,
+    pub aux_watermark: u32,
+    pub sample_max_stack: u16,
+    __reserved_2: u16,
+}
+
+impl Default for perf_event_attr {
+    fn default() -> Self {
+        // Safe: every field is a plain integer and zero is a valid value
+        // for all of them (zeroed attr == "count this event, no frills").
+        unsafe { ::std::mem::zeroed() }
+    }
+}
+
+impl perf_event_attr {
+    pub fn flag(&self, bit: u8) -> bool {
+        (self.flags >> bit) & 1 == 1
+    }
+
+    pub fn set_flag(&mut self, bit: u8, value: bool) {
+        if value {
+            self.flags |= 1 << bit;
+        } else {
+            self.flags &= !(1 << bit);
+        }
+    }
+}
+
+/// `struct perf_event_header`, present at the start of every ring-buffer
+/// record.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct perf_event_header {
+    pub type_: u32,
+    pub misc: u16,
+    pub size: u16,
+}
+
+/// `struct perf_event_mmap_page`, the control page at the start of a
+/// perf ring-buffer mapping.
+#[repr(C)]
+pub struct perf_event_mmap_page {
+    pub version: u32,
+    pub compat_version: u32,
+    pub lock: u32,
+    pub index: u32,
+    pub offset: i64,
+    pub time_enabled: u64,
+    pub time_running: u64,
+    pub capabilities: u64,
+    pub pmc_width: u16,
+    pub time_shift: u16,
+    pub time_mult: u32,
+    pub time_offset: u64,
+    pub time_zero: u64,
+    pub size: u32,
+    __reserved_1: u32,
+    __reserved: [u64; 118],
+    pub data_head: u64,
+    pub data_tail: u64,
+    pub data_offset: u64,
+    pub data_size: u64,
+    pub aux_head: u64,
+    pub aux_tail: u64,
+    pub aux_offset: u64,
+    pub aux_size: u64,
+}
+
+// perf_event_header.misc
+pub const PERF_RECORD_MISC_CPUMODE_MASK: u32 = 7;
+pub const PERF_RECORD_MISC_CPUMODE_UNKNOWN: u32 = 0;
+pub const PERF_RECORD_MISC_KERNEL: u32 = 1;
+pub const PERF_RECORD_MISC_USER: u32 = 2;
+pub const PERF_RECORD_MISC_HYPERVISOR: u32 = 3;
+pub const PERF_RECORD_MISC_GUEST_KERNEL: u32 = 4;
+pub const PERF_RECORD_MISC_GUEST_USER: u32 = 5;
+pub const PERF_RECORD_MISC_MMAP_DATA: u32 = 1 << 13;
+pub const PERF_RECORD_MISC_COMM_EXEC: u32 = 1 << 13;
+pub const PERF_RECORD_MISC_SWITCH_OUT: u32 = 1 << 13;
+pub const PERF_RECORD_MISC_EXACT_IP: u32 = 1 << 14;
+pub const PERF_RECORD_MISC_EXT_RESERVED: u32 = 1 << 15;
+
+pub mod perf_event_type {
+    pub const PERF_RECORD_MMAP: u32 = 1;
+    pub const PERF_RECORD_LOST: u32 = 2;
+    pub const PERF_RECORD_COMM: u32 = 3;
+    pub const PERF_RECORD_EXIT: u32 = 4;
+    pub const PERF_RECORD_THROTTLE: u32 = 5;
+    pub const PERF_RECORD_UNTHROTTLE: u32 = 6;
+    pub const PERF_RECORD_FORK: u32 = 7;
+    pub const PERF_RECORD_READ: u32 = 8;
+    pub const PERF_RECORD_SAMPLE: u32 = 9;
+    pub const PERF_RECORD_MMAP2: u32 = 10;
+    pub const PERF_RECORD_AUX: u32 = 11;
+    pub const PERF_RECORD_ITRACE_START: u32 = 12;
+    pub const PERF_RECORD_LOST_SAMPLES: u32 = 13;
+    pub const PERF_RECORD_SWITCH: u32 = 14;
+    pub const PERF_RECORD_SWITCH_CPU_WIDE: u32 = 15;
+    pub const PERF_RECORD_NAMESPACES: u32 = 16;
+    pub const PERF_RECORD_KSYMBOL: u32 = 17;
+    pub const PERF_RECORD_BPF_EVENT: u32 = 18;
+    pub const PERF_RECORD_CGROUP: u32 = 19;
+    pub const PERF_RECORD_TEXT_POKE: u32 = 20;
+}
+
+// PERF_RECORD_AUX.flags
+pub const PERF_AUX_FLAG_TRUNCATED: u64 = 1 << 0;
+pub const PERF_AUX_FLAG_OVERWRITE: u64 = 1 << 1;
+
+/// Page size used for ring-buffer mmap sizing.
+///
+/// `perf_event_open(2)` requires the mmap length to be `1 + 2^n` pages, so
+/// callers size their buffer in terms of this constant rather than hardcoding
+/// `4096`.
+pub fn page_size() -> c_ulong {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as c_ulong }
+}
@@ -0,0 +1,532 @@
+//! Sampling/profiling mode: `mmap()` the kernel's ring buffer for a perf
+//! event and decode the records it writes, as opposed to [`::Counts`]'s
+//! plain counting mode.
+
+pub mod record;
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+
+use libc::c_void;
+
+use error::PerfEventsError;
+use events::Event;
+use raw::{self, attr_flag_bits, perf_event_attr, perf_event_mmap_page};
+use sys;
+use {CpuConfig, PidConfig};
+
+use self::record::{Record, RecordDecoder};
+
+/// How a [`Samples`] counter decides when to emit a record.
+#[derive(Clone, Copy, Debug)]
+pub enum SamplePeriod {
+    /// Emit a sample every `n` occurrences of the event.
+    Period(u64),
+    /// Emit samples at approximately this frequency (Hz); the kernel
+    /// adjusts the underlying period to hit it.
+    Frequency(u64),
+}
+
+impl Samples {
+    /// Start building a sampling counter for `event`, parallel to
+    /// [`::Counts::new`] for plain counting.
+    pub fn new(event: Event, pid: PidConfig, cpu: CpuConfig) -> SamplesBuilder {
+        SamplesBuilder::new(event, pid, cpu)
+    }
+}
+
+#[derive(Debug)]
+pub struct SamplesBuilder {
+    event: Event,
+    pid: PidConfig,
+    cpu: CpuConfig,
+    period: SamplePeriod,
+    sample_type: u64,
+    /// `1 + 2^mmap_pages_order` pages are mapped for the ring buffer.
+    mmap_pages_order: u32,
+    context_switch: bool,
+    /// `2^n` pages for the AUX buffer (Intel PT / BTS trace data), if set.
+    aux_pages_order: Option<u32>,
+    sample_id_all: bool,
+    namespaces: bool,
+    ksymbol: bool,
+    bpf_event: bool,
+    cgroup: bool,
+    text_poke: bool,
+    /// `branch_sample_type` filter mask; implies `PERF_SAMPLE_BRANCH_STACK`
+    /// in `sample_type` when set.
+    branch_sample_type: Option<u64>,
+}
+
+impl SamplesBuilder {
+    pub(crate) fn new(event: Event, pid: PidConfig, cpu: CpuConfig) -> Self {
+        SamplesBuilder {
+            event,
+            pid,
+            cpu,
+            period: SamplePeriod::Period(1),
+            sample_type: raw::PERF_SAMPLE_IP | raw::PERF_SAMPLE_TID | raw::PERF_SAMPLE_TIME,
+            mmap_pages_order: 3, // 1 + 8 pages
+            context_switch: false,
+            aux_pages_order: None,
+            sample_id_all: false,
+            namespaces: false,
+            ksymbol: false,
+            bpf_event: false,
+            cgroup: false,
+            text_poke: false,
+            branch_sample_type: None,
+        }
+    }
+
+    /// Request branch-stack (LBR) sampling: each `PERF_RECORD_SAMPLE` will
+    /// carry the recent branches (see [`record::BranchEntry`]) matching
+    /// `branch_sample_type`, a mask of `raw::PERF_SAMPLE_BRANCH_*` flags
+    /// (e.g. `PERF_SAMPLE_BRANCH_USER | PERF_SAMPLE_BRANCH_ANY` for every
+    /// userspace branch, or `| PERF_SAMPLE_BRANCH_IND_JUMP` /
+    /// `| PERF_SAMPLE_BRANCH_CALL` to narrow it to indirect jumps or direct
+    /// calls). Implies `PERF_SAMPLE_BRANCH_STACK` in `sample_type`.
+    pub fn branch_stack(mut self, branch_sample_type: u64) -> Self {
+        self.branch_sample_type = Some(branch_sample_type);
+        self
+    }
+
+    /// Request `PERF_RECORD_NAMESPACES` records, reporting the dev/inode of
+    /// each namespace a task belongs to (container-aware symbolization).
+    pub fn namespaces(mut self, enabled: bool) -> Self {
+        self.namespaces = enabled;
+        self
+    }
+
+    /// Request `PERF_RECORD_KSYMBOL` records for dynamic kernel symbol
+    /// (BPF/ftrace) register/unregister events.
+    pub fn ksymbol(mut self, enabled: bool) -> Self {
+        self.ksymbol = enabled;
+        self
+    }
+
+    /// Request `PERF_RECORD_BPF_EVENT` records for BPF program load/unload.
+    pub fn bpf_event(mut self, enabled: bool) -> Self {
+        self.bpf_event = enabled;
+        self
+    }
+
+    /// Request `PERF_RECORD_CGROUP` records when cgroups are created.
+    pub fn cgroup(mut self, enabled: bool) -> Self {
+        self.cgroup = enabled;
+        self
+    }
+
+    /// Request `PERF_RECORD_TEXT_POKE` records for self-modifying/patched
+    /// code (ftrace, static keys, alternatives).
+    pub fn text_poke(mut self, enabled: bool) -> Self {
+        self.text_poke = enabled;
+        self
+    }
+
+    /// Tag every record (not just `PERF_RECORD_SAMPLE`) with a trailing
+    /// `sample_id`, so e.g. `PERF_RECORD_MMAP2`/`SWITCH`/`AUX` records can be
+    /// correlated with samples by time, CPU, or stream ID. See
+    /// [`record::SampleId`].
+    pub fn sample_id_all(mut self, enabled: bool) -> Self {
+        self.sample_id_all = enabled;
+        self
+    }
+
+    /// Map an AUX buffer of `2^order` pages alongside the main ring, for
+    /// hardware trace events (Intel PT, BTS, ...) that stream through
+    /// `PERF_RECORD_AUX` rather than `PERF_RECORD_SAMPLE`.
+    pub fn aux_buffer(mut self, order: u32) -> Self {
+        self.aux_pages_order = Some(order);
+        self
+    }
+
+    /// Request `PERF_RECORD_SWITCH`/`PERF_RECORD_SWITCH_CPU_WIDE` records.
+    /// Unlike tracepoint/software context-switch events, these deliver full
+    /// switch-in/out data even under restrictive `perf_event_paranoid`
+    /// settings.
+    pub fn context_switch(mut self, enabled: bool) -> Self {
+        self.context_switch = enabled;
+        self
+    }
+
+    pub fn period(mut self, period: SamplePeriod) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn sample_type(mut self, sample_type: u64) -> Self {
+        self.sample_type = sample_type;
+        self
+    }
+
+    /// Size the ring buffer as `1 + 2^order` pages.
+    pub fn mmap_pages_order(mut self, order: u32) -> Self {
+        self.mmap_pages_order = order;
+        self
+    }
+
+    pub fn create(self) -> Result<Samples, PerfEventsError> {
+        let mut attr = perf_event_attr::default();
+        let (type_, config) = self.event.type_and_config();
+        attr.type_ = type_;
+        attr.config = config;
+        attr.size = ::std::mem::size_of::<perf_event_attr>() as u32;
+
+        let mut sample_type = self.sample_type;
+        if let Some(branch_sample_type) = self.branch_sample_type {
+            sample_type |= raw::PERF_SAMPLE_BRANCH_STACK;
+            attr.branch_sample_type = branch_sample_type;
+        }
+        attr.sample_type = sample_type;
+
+        match self.period {
+            SamplePeriod::Period(p) => attr.sample_period_or_freq = p,
+            SamplePeriod::Frequency(f) => {
+                attr.set_flag(attr_flag_bits::FREQ, true);
+                attr.sample_period_or_freq = f;
+            }
+        }
+        attr.set_flag(attr_flag_bits::DISABLED, true);
+        attr.set_flag(attr_flag_bits::MMAP, true);
+        attr.set_flag(attr_flag_bits::MMAP2, true);
+        attr.set_flag(attr_flag_bits::COMM, true);
+        attr.set_flag(attr_flag_bits::TASK, true);
+        attr.set_flag(attr_flag_bits::CONTEXT_SWITCH, self.context_switch);
+        attr.set_flag(attr_flag_bits::SAMPLE_ID_ALL, self.sample_id_all);
+        attr.set_flag(attr_flag_bits::NAMESPACES, self.namespaces);
+        attr.set_flag(attr_flag_bits::KSYMBOL, self.ksymbol);
+        attr.set_flag(attr_flag_bits::BPF_EVENT, self.bpf_event);
+        attr.set_flag(attr_flag_bits::CGROUP, self.cgroup);
+        attr.set_flag(attr_flag_bits::TEXT_POKE, self.text_poke);
+
+        let fd = sys::perf_event_open(&attr, self.pid.raw(), self.cpu.raw(), -1, 0)
+            .map_err(|why| PerfEventsError::StartError {
+                inner: format!("{}", why),
+            })?;
+
+        let mut ring = RingBuffer::new(fd, self.mmap_pages_order, sample_type, self.sample_id_all)
+            .map_err(|why| PerfEventsError::StartError {
+                inner: format!("mmap failed: {}", why),
+            })?;
+
+        let aux = match self.aux_pages_order {
+            Some(order) => Some(ring.map_aux(fd, order).map_err(|why| {
+                PerfEventsError::StartError {
+                    inner: format!("aux mmap failed: {}", why),
+                }
+            })?),
+            None => None,
+        };
+
+        Ok(Samples {
+            event: self.event,
+            fd,
+            ring,
+            aux,
+            lost_samples: 0,
+        })
+    }
+}
+
+pub struct Samples {
+    event: Event,
+    fd: RawFd,
+    ring: RingBuffer,
+    aux: Option<AuxBuffer>,
+    /// Running total of `PERF_RECORD_LOST_SAMPLES.lost`, accumulated as
+    /// `poll()` drains them. Hardware (PEBS) sample loss, as opposed to the
+    /// ring-buffer-level loss `PERF_RECORD_LOST` reports.
+    lost_samples: u64,
+}
+
+impl Samples {
+    pub fn event(&self) -> Event {
+        self.event
+    }
+
+    /// Total samples this event's hardware has reported as dropped (via
+    /// `PERF_RECORD_LOST_SAMPLES`) since this counter was created.
+    pub fn lost_samples(&self) -> u64 {
+        self.lost_samples
+    }
+
+    /// Read the bytes a `PERF_RECORD_AUX` record (see [`record::AuxRecord`])
+    /// reported as newly landed, and advance the AUX tail past them so the
+    /// kernel can reuse that space. Returns `None` if no AUX buffer was
+    /// requested via `SamplesBuilder::aux_buffer`.
+    pub fn read_aux(&mut self, offset: u64, size: u64) -> Option<Vec<u8>> {
+        self.aux.as_mut().map(|aux| aux.read(offset, size))
+    }
+
+    pub fn start(&self) -> Result<(), PerfEventsError> {
+        unsafe { sys::enable(self.fd, 0) }
+            .map(|_| ())
+            .map_err(|inner| PerfEventsError::IoctlError { inner })
+    }
+
+    pub fn stop(&self) -> Result<(), PerfEventsError> {
+        unsafe { sys::disable(self.fd, 0) }
+            .map(|_| ())
+            .map_err(|inner| PerfEventsError::IoctlError { inner })
+    }
+
+    /// Drain whatever records the kernel has written since the last poll.
+    pub fn poll(&mut self) -> Vec<Record> {
+        let records = self.ring.drain();
+
+        for record in &records {
+            if let record::RecordContents::LostSamples(ref lost) = record.contents {
+                self.lost_samples += lost.lost;
+            }
+        }
+
+        records
+    }
+}
+
+impl AsRawFd for Samples {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Samples {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// The mmap'd perf ring buffer: a `perf_event_mmap_page` control page
+/// followed by the data pages themselves.
+struct RingBuffer {
+    base: *mut c_void,
+    len: usize,
+    data_len: u64,
+    decoder: RecordDecoder,
+    /// Holds a record's bytes when they straddle the end of the ring, so we
+    /// can hand the decoder a contiguous slice.
+    scratch: Vec<u8>,
+}
+
+impl RingBuffer {
+    fn new(fd: RawFd, pages_order: u32, sample_type: u64, sample_id_all: bool) -> io::Result<Self> {
+        let page_size = raw::page_size() as usize;
+        let data_pages = 1usize << pages_order;
+        let len = page_size * (1 + data_pages);
+
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RingBuffer {
+            base,
+            len,
+            data_len: (data_pages * page_size) as u64,
+            decoder: RecordDecoder::new(sample_type, sample_id_all),
+            scratch: Vec::new(),
+        })
+    }
+
+    fn header(&self) -> &perf_event_mmap_page {
+        unsafe { &*(self.base as *const perf_event_mmap_page) }
+    }
+
+    /// Set up the AUX area: stake out `[aux_offset, aux_offset + aux_size)`
+    /// in the control page (placed just past our own mapping, since nothing
+    /// else is using that address space) and mmap it as a second region.
+    fn map_aux(&mut self, fd: RawFd, pages_order: u32) -> io::Result<AuxBuffer> {
+        let page_size = raw::page_size() as usize;
+        let aux_len = (1usize << pages_order) * page_size;
+        let aux_offset = self.len as u64;
+
+        unsafe {
+            let header = self.base as *mut perf_event_mmap_page;
+            ptr::write_volatile(&mut (*header).aux_offset, aux_offset);
+            ptr::write_volatile(&mut (*header).aux_size, aux_len as u64);
+        }
+
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                aux_len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                fd,
+                aux_offset as libc::off_t,
+            )
+        };
+
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(AuxBuffer {
+            base,
+            len: aux_len,
+            ring_base: self.base,
+        })
+    }
+
+    fn data_ptr(&self) -> *const u8 {
+        let page_size = raw::page_size() as usize;
+        unsafe { (self.base as *const u8).add(page_size) }
+    }
+
+    /// Read every complete record currently available, advancing
+    /// `data_tail` past what was consumed.
+    fn drain(&mut self) -> Vec<Record> {
+        use bytes::BytesMut;
+        use tokio_codec::Decoder;
+
+        let head = unsafe { ptr::read_volatile(&self.header().data_head) };
+        let mut tail = unsafe { ptr::read_volatile(&self.header().data_tail) };
+
+        let mut records = Vec::new();
+
+        while head.wrapping_sub(tail) >= ::std::mem::size_of::<raw::perf_event_header>() as u64 {
+            let offset = (tail % self.data_len) as usize;
+            let header = self.read_header_at(offset);
+            let record_len = header.size as usize;
+
+            let mut buf = BytesMut::with_capacity(record_len);
+            self.copy_record(offset, record_len, &mut buf);
+
+            match self.decoder.decode(&mut buf) {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => break,
+                Err(why) => {
+                    debug!("failed to decode perf record: {}", why);
+                }
+            }
+
+            tail = tail.wrapping_add(record_len as u64);
+        }
+
+        // Pairs with the volatile read of data_head above: make sure the
+        // kernel doesn't see data_tail move until we're done reading the
+        // records it guards.
+        ::std::sync::atomic::fence(::std::sync::atomic::Ordering::SeqCst);
+        unsafe {
+            ptr::write_volatile(&mut (*(self.base as *mut perf_event_mmap_page)).data_tail, tail);
+        }
+
+        records
+    }
+
+    fn read_header_at(&self, offset: usize) -> raw::perf_event_header {
+        let mut scratch = [0u8; 8];
+        self.copy_from_ring(offset, &mut scratch);
+        unsafe { ::std::mem::transmute(scratch) }
+    }
+
+    /// Copy `len` bytes starting at `offset` within the data area into
+    /// `dst`, wrapping around the ring boundary if the record straddles it.
+    fn copy_record(&mut self, offset: usize, len: usize, dst: &mut bytes::BytesMut) {
+        // Take `scratch` out so `copy_from_ring`'s `&self` receiver doesn't
+        // overlap with a `&mut` borrow of the same field, then hand it back.
+        let mut scratch = ::std::mem::replace(&mut self.scratch, Vec::new());
+        scratch.resize(len, 0);
+        self.copy_from_ring(offset, &mut scratch);
+        dst.extend_from_slice(&scratch);
+        self.scratch = scratch;
+    }
+
+    fn copy_from_ring(&self, offset: usize, dst: &mut [u8]) {
+        let data_len = self.data_len as usize;
+        let first_chunk = (data_len - offset).min(dst.len());
+        unsafe {
+            ptr::copy_nonoverlapping(self.data_ptr().add(offset), dst.as_mut_ptr(), first_chunk);
+        }
+        if first_chunk < dst.len() {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.data_ptr(),
+                    dst.as_mut_ptr().add(first_chunk),
+                    dst.len() - first_chunk,
+                );
+            }
+        }
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base, self.len);
+        }
+    }
+}
+
+unsafe impl Send for RingBuffer {}
+
+/// The second mmap region used for hardware trace data (Intel PT / BTS):
+/// a plain byte ring, drained independently of the main event stream by
+/// whatever `aux_offset`/`aux_size` a `PERF_RECORD_AUX` reports.
+struct AuxBuffer {
+    base: *mut c_void,
+    len: usize,
+    /// The main ring's control page, which also holds `aux_head`/`aux_tail`.
+    ring_base: *mut c_void,
+}
+
+impl AuxBuffer {
+    /// Copy `size` bytes starting at ring-relative `offset` out of the AUX
+    /// mapping, then advance `aux_tail` past them.
+    fn read(&mut self, offset: u64, size: u64) -> Vec<u8> {
+        let aux_len = self.len as u64;
+        let start = (offset % aux_len) as usize;
+        let size = size as usize;
+
+        let mut out = vec![0u8; size];
+        let first_chunk = (self.len - start).min(size);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                (self.base as *const u8).add(start),
+                out.as_mut_ptr(),
+                first_chunk,
+            );
+            if first_chunk < size {
+                ptr::copy_nonoverlapping(
+                    self.base as *const u8,
+                    out.as_mut_ptr().add(first_chunk),
+                    size - first_chunk,
+                );
+            }
+        }
+
+        ::std::sync::atomic::fence(::std::sync::atomic::Ordering::SeqCst);
+        unsafe {
+            ptr::write_volatile(
+                &mut (*(self.ring_base as *mut perf_event_mmap_page)).aux_tail,
+                offset + size as u64,
+            );
+        }
+
+        out
+    }
+}
+
+impl Drop for AuxBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base, self.len);
+        }
+    }
+}
+
+unsafe impl Send for AuxBuffer {}
@@ -1,31 +1,723 @@
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 use num::FromPrimitive;
 use tokio_codec::Decoder;
 
 use error::Error;
 use raw::*;
 
-pub struct RecordDecoder;
+/// Decodes whole ring-buffer records out of a byte buffer.
+///
+/// One `RingBuffer::drain` call feeds this one already-contiguous record
+/// (header + body) at a time, so unlike a typical `tokio_codec` use this
+/// never has to return `Ok(None)` for "need more bytes" in practice -- but
+/// it's kept as an honest `Decoder` so the same parsing logic could later
+/// be driven off a byte stream instead of the mmap ring.
+pub struct RecordDecoder {
+    /// The `sample_type` the originating counter was opened with; needed to
+    /// know which optional fields a `PERF_RECORD_SAMPLE` body -- and, when
+    /// `sample_id_all` is set, the trailing `sample_id` of every other
+    /// record -- contains.
+    sample_type: u64,
+    /// Whether the counter was opened with `sample_id_all`, i.e. whether
+    /// non-`PERF_RECORD_SAMPLE` records carry a trailing [`SampleId`].
+    sample_id_all: bool,
+}
+
+impl RecordDecoder {
+    pub fn new(sample_type: u64, sample_id_all: bool) -> Self {
+        RecordDecoder {
+            sample_type,
+            sample_id_all,
+        }
+    }
+}
 
 impl Decoder for RecordDecoder {
     type Item = Record;
     type Error = Error;
 
-    fn decode(&mut self, _src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        unimplemented!();
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < ::std::mem::size_of::<perf_event_header>() {
+            return Ok(None);
+        }
+
+        let header = EventHeader::from(&read_header(&src[..]));
+        if src.len() < header.size as usize {
+            return Ok(None);
+        }
+
+        let metadata = header.misc;
+        let mut body = src.split_to(header.size as usize);
+        body.advance(::std::mem::size_of::<perf_event_header>());
+
+        let sample_type = self.sample_type;
+        let sample_id_all = self.sample_id_all;
+
+        let contents = match SampledEventType::from_u32(header.event_type) {
+            Some(SampledEventType::Aux) => RecordContents::Aux(AuxRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::Comm) => RecordContents::Comm(CommRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::Exit) => RecordContents::Exit(ExitRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::Mmap2) => RecordContents::Mmap2(Mmap2Record::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::Sample) => {
+                RecordContents::Sample(SampleRecord::parse(&mut body, sample_type))
+            }
+            Some(SampledEventType::LostSamples) => RecordContents::LostSamples(
+                LostSamplesRecord::parse(&mut body, sample_type, sample_id_all),
+            ),
+            Some(SampledEventType::Switch) => RecordContents::Switch(SwitchRecord::parse(
+                &mut body,
+                metadata.switched_out(),
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::SwitchCpuWide) => RecordContents::SwitchCpuWide(
+                SwitchCpuWideRecord::parse(
+                    &mut body,
+                    metadata.switched_out(),
+                    sample_type,
+                    sample_id_all,
+                ),
+            ),
+            Some(SampledEventType::Namespaces) => RecordContents::Namespaces(
+                NamespacesRecord::parse(&mut body, sample_type, sample_id_all),
+            ),
+            Some(SampledEventType::Ksymbol) => RecordContents::Ksymbol(KsymbolRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::BpfEvent) => RecordContents::BpfEvent(BpfEventRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::Cgroup) => RecordContents::Cgroup(CgroupRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(SampledEventType::TextPoke) => RecordContents::TextPoke(TextPokeRecord::parse(
+                &mut body,
+                sample_type,
+                sample_id_all,
+            )),
+            Some(other) => RecordContents::Unknown(other as u32),
+            None => RecordContents::Unknown(header.event_type),
+        };
+
+        Ok(Some(Record {
+            metadata,
+            contents,
+        }))
     }
 }
 
+fn read_header(buf: &[u8]) -> perf_event_header {
+    assert!(buf.len() >= ::std::mem::size_of::<perf_event_header>());
+    unsafe { ::std::ptr::read_unaligned(buf.as_ptr() as *const perf_event_header) }
+}
+
 pub struct Record {
-    _metadata: Metadata,
-    _contents: RecordContents,
+    pub(crate) metadata: Metadata,
+    pub contents: RecordContents,
+}
+
+pub enum RecordContents {
+    Aux(AuxRecord),
+    BpfEvent(BpfEventRecord),
+    Cgroup(CgroupRecord),
+    Comm(CommRecord),
+    Exit(ExitRecord),
+    Ksymbol(KsymbolRecord),
+    LostSamples(LostSamplesRecord),
+    Mmap2(Mmap2Record),
+    Namespaces(NamespacesRecord),
+    Sample(SampleRecord),
+    Switch(SwitchRecord),
+    SwitchCpuWide(SwitchCpuWideRecord),
+    TextPoke(TextPokeRecord),
+    /// A record type this crate doesn't decode the body of yet, or a raw
+    /// `perf_event_header.type` value that isn't in `SampledEventType` at
+    /// all (e.g. `PERF_RECORD_UNTHROTTLE`).
+    Unknown(u32),
+}
+
+/// `PERF_RECORD_COMM`: the process changed its name (e.g. via `exec`).
+pub struct CommRecord {
+    pub pid: u32,
+    pub tid: u32,
+    pub comm: String,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl CommRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        let pid = buf.get_u32_le();
+        let tid = buf.get_u32_le();
+        let comm = read_cstr(buf);
+        let sample_id = SampleId::parse_if_enabled(buf, sample_type, sample_id_all);
+        CommRecord {
+            pid,
+            tid,
+            comm,
+            sample_id,
+        }
+    }
+}
+
+/// `PERF_RECORD_EXIT`: a process (or thread) exited.
+pub struct ExitRecord {
+    pub pid: u32,
+    pub ppid: u32,
+    pub tid: u32,
+    pub ptid: u32,
+    pub time: u64,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl ExitRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        ExitRecord {
+            pid: buf.get_u32_le(),
+            ppid: buf.get_u32_le(),
+            tid: buf.get_u32_le(),
+            ptid: buf.get_u32_le(),
+            time: buf.get_u64_le(),
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_LOST_SAMPLES` (since Linux 4.2): hardware sampling (Intel
+/// PEBS and similar) dropped some number of samples before the kernel could
+/// write them out. Distinct from the ring-buffer-level `PERF_RECORD_LOST`,
+/// which instead reports records the *consumer* didn't drain in time.
+pub struct LostSamplesRecord {
+    /// The number of samples the hardware reports as dropped.
+    pub lost: u64,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl LostSamplesRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        LostSamplesRecord {
+            lost: buf.get_u64_le(),
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_MMAP2`: an executable mapping was created, with enough
+/// detail (device/inode) to uniquely identify shared mappings.
+pub struct Mmap2Record {
+    pub pid: u32,
+    pub tid: u32,
+    pub addr: u64,
+    pub len: u64,
+    pub pgoff: u64,
+    pub maj: u32,
+    pub min: u32,
+    pub ino: u64,
+    pub ino_generation: u64,
+    pub prot: u32,
+    pub flags: u32,
+    pub filename: String,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl Mmap2Record {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        Mmap2Record {
+            pid: buf.get_u32_le(),
+            tid: buf.get_u32_le(),
+            addr: buf.get_u64_le(),
+            len: buf.get_u64_le(),
+            pgoff: buf.get_u64_le(),
+            maj: buf.get_u32_le(),
+            min: buf.get_u32_le(),
+            ino: buf.get_u64_le(),
+            ino_generation: buf.get_u64_le(),
+            prot: buf.get_u32_le(),
+            flags: buf.get_u32_le(),
+            filename: read_cstr(buf),
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_SAMPLE`, decoded according to the `sample_type` bits the
+/// counter was opened with.
+///
+/// Only the fields this crate currently has a use for (enough to
+/// reconstruct "what code was running", plus branch-stack sampling) are
+/// actually decoded into this struct; every other field `sample_type` can
+/// select ahead of `branch_stack` is still consumed -- by its ABI width,
+/// not just skipped -- so that `branch_stack` lands at the right offset
+/// regardless of what else is set. Fields after `branch_stack` in the ABI
+/// order (`REGS_USER`, `STACK_USER`, `WEIGHT`, ...) are not read.
+#[derive(Default)]
+pub struct SampleRecord {
+    pub ip: Option<u64>,
+    pub pid: Option<u32>,
+    pub tid: Option<u32>,
+    pub time: Option<u64>,
+    /// Present when `PERF_SAMPLE_BRANCH_STACK` is set: the most recent
+    /// branches (LBR), filtered by `branch_sample_type`.
+    pub branch_stack: Option<Vec<BranchEntry>>,
+}
+
+impl SampleRecord {
+    /// `sample_type` must match what the originating counter was opened
+    /// with, since that's the only thing that tells us which fields are
+    /// present and in what order. The ABI order is: `IDENTIFIER`, `IP`,
+    /// `TID`, `TIME`, `ADDR`, `ID`, `STREAM_ID`, `CPU`, `PERIOD`, `READ`,
+    /// `CALLCHAIN`, `RAW`, `BRANCH_STACK`.
+    fn parse(buf: &mut BytesMut, sample_type: u64) -> Self {
+        let mut sample = SampleRecord::default();
+
+        if sample_type & PERF_SAMPLE_IDENTIFIER != 0 {
+            buf.advance(8);
+        }
+        if sample_type & PERF_SAMPLE_IP != 0 {
+            sample.ip = Some(buf.get_u64_le());
+        }
+        if sample_type & PERF_SAMPLE_TID != 0 {
+            sample.pid = Some(buf.get_u32_le());
+            sample.tid = Some(buf.get_u32_le());
+        }
+        if sample_type & PERF_SAMPLE_TIME != 0 {
+            sample.time = Some(buf.get_u64_le());
+        }
+        if sample_type & PERF_SAMPLE_ADDR != 0 {
+            buf.advance(8);
+        }
+        if sample_type & PERF_SAMPLE_ID != 0 {
+            buf.advance(8);
+        }
+        if sample_type & PERF_SAMPLE_STREAM_ID != 0 {
+            buf.advance(8);
+        }
+        if sample_type & PERF_SAMPLE_CPU != 0 {
+            buf.advance(8); // u32 cpu, u32 res
+        }
+        if sample_type & PERF_SAMPLE_PERIOD != 0 {
+            buf.advance(8);
+        }
+        if sample_type & PERF_SAMPLE_READ != 0 {
+            // `SamplesBuilder` never sets `attr.read_format`, so the
+            // `read_format` struct here is always the bare `{ u64 value; }`
+            // (no `TOTAL_TIME_ENABLED`/`_RUNNING`/`ID`/`GROUP` bits).
+            buf.advance(8);
+        }
+        if sample_type & PERF_SAMPLE_CALLCHAIN != 0 {
+            let nr = buf.get_u64_le();
+            buf.advance((nr * 8) as usize);
+        }
+        if sample_type & PERF_SAMPLE_RAW != 0 {
+            let size = buf.get_u32_le();
+            buf.advance(size as usize);
+        }
+        if sample_type & PERF_SAMPLE_BRANCH_STACK != 0 {
+            let bnr = buf.get_u64_le();
+            let mut branches = Vec::with_capacity(bnr as usize);
+            for _ in 0..bnr {
+                branches.push(BranchEntry::parse(buf));
+            }
+            sample.branch_stack = Some(branches);
+        }
+
+        sample
+    }
+}
+
+/// One entry of a `PERF_SAMPLE_BRANCH_STACK` branch stack (a single
+/// `struct perf_branch_entry`).
+#[derive(Clone, Copy, Debug)]
+pub struct BranchEntry {
+    /// The source instruction (may not itself be a branch).
+    pub from: u64,
+    /// The branch target.
+    pub to: u64,
+    /// The branch target was mispredicted.
+    pub mispred: bool,
+    /// The branch target was predicted.
+    pub predicted: bool,
+    /// The branch was in a transactional memory transaction (since Linux 3.11).
+    pub in_tx: bool,
+    /// The branch was in an aborted transactional memory transaction (since Linux 3.11).
+    pub abort: bool,
+    /// Cycles elapsed since the previous branch stack update (since Linux 4.3);
+    /// `0` if unsupported by the hardware.
+    pub cycles: u16,
+}
+
+impl BranchEntry {
+    fn parse(buf: &mut BytesMut) -> Self {
+        let from = buf.get_u64_le();
+        let to = buf.get_u64_le();
+        let flags = buf.get_u64_le();
+
+        BranchEntry {
+            from,
+            to,
+            mispred: flags & 1 != 0,
+            predicted: (flags >> 1) & 1 != 0,
+            in_tx: (flags >> 2) & 1 != 0,
+            abort: (flags >> 3) & 1 != 0,
+            cycles: ((flags >> 4) & 0xffff) as u16,
+        }
+    }
+}
+
+/// `PERF_RECORD_AUX`: new data has landed in the separate AUX buffer
+/// region (Intel PT / BTS trace data, typically), at `[aux_offset,
+/// aux_offset + aux_size)` of the AUX ring.
+pub struct AuxRecord {
+    pub aux_offset: u64,
+    pub aux_size: u64,
+    pub flags: AuxFlags,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl AuxRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        AuxRecord {
+            aux_offset: buf.get_u64_le(),
+            aux_size: buf.get_u64_le(),
+            flags: AuxFlags::from(buf.get_u64_le()),
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// Decoded `PERF_RECORD_AUX.flags`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuxFlags {
+    /// `PERF_AUX_FLAG_TRUNCATED`: the AUX buffer filled up faster than the
+    /// consumer drained it, so some data here was dropped.
+    pub truncated: bool,
+    /// `PERF_AUX_FLAG_OVERWRITE`: the AUX buffer is in overwrite mode and
+    /// this data has overwritten data that hadn't been read yet.
+    pub overwrite: bool,
+}
+
+impl From<u64> for AuxFlags {
+    fn from(flags: u64) -> Self {
+        AuxFlags {
+            truncated: flags & PERF_AUX_FLAG_TRUNCATED != 0,
+            overwrite: flags & PERF_AUX_FLAG_OVERWRITE != 0,
+        }
+    }
+}
+
+/// `PERF_RECORD_SWITCH`: a context switch happened. Carries no body of its
+/// own; everything we know about it comes from the header's misc bits.
+pub struct SwitchRecord {
+    /// `true` if this is a switch away from the current process, `false`
+    /// if it's a switch into it (`PERF_RECORD_MISC_SWITCH_OUT`).
+    pub switched_out: bool,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl SwitchRecord {
+    fn parse(buf: &mut BytesMut, switched_out: bool, sample_type: u64, sample_id_all: bool) -> Self {
+        SwitchRecord {
+            switched_out,
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_SWITCH_CPU_WIDE`: as [`SwitchRecord`], but only emitted
+/// when sampling CPU-wide, with the process being switched to/from.
+pub struct SwitchCpuWideRecord {
+    pub switched_out: bool,
+    /// The process ID of the previous (if switching in) or next (if
+    /// switching out) process on the CPU.
+    pub next_prev_pid: u32,
+    /// The thread ID of the previous (if switching in) or next (if
+    /// switching out) thread on the CPU.
+    pub next_prev_tid: u32,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
 }
 
-pub enum RecordContents {}
+impl SwitchCpuWideRecord {
+    fn parse(buf: &mut BytesMut, switched_out: bool, sample_type: u64, sample_id_all: bool) -> Self {
+        SwitchCpuWideRecord {
+            switched_out,
+            next_prev_pid: buf.get_u32_le(),
+            next_prev_tid: buf.get_u32_le(),
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// The trailing `sample_id` struct the kernel appends to every
+/// non-`PERF_RECORD_SAMPLE` record when `sample_id_all` is set, so those
+/// records can still be correlated with samples by time/CPU/stream.
+///
+/// Which fields are present -- and in what order -- is controlled by the
+/// same `sample_type` mask as `PERF_RECORD_SAMPLE` itself; the ABI always
+/// lays them out as TID, TIME, ID, STREAM_ID, CPU, then IDENTIFIER.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SampleId {
+    pub pid: Option<u32>,
+    pub tid: Option<u32>,
+    pub time: Option<u64>,
+    pub id: Option<u64>,
+    pub stream_id: Option<u64>,
+    pub cpu: Option<u32>,
+}
+
+impl SampleId {
+    fn parse(buf: &mut BytesMut, sample_type: u64) -> Self {
+        let mut sample_id = SampleId::default();
+
+        if sample_type & PERF_SAMPLE_TID != 0 {
+            sample_id.pid = Some(buf.get_u32_le());
+            sample_id.tid = Some(buf.get_u32_le());
+        }
+        if sample_type & PERF_SAMPLE_TIME != 0 {
+            sample_id.time = Some(buf.get_u64_le());
+        }
+        if sample_type & PERF_SAMPLE_ID != 0 {
+            sample_id.id = Some(buf.get_u64_le());
+        }
+        if sample_type & PERF_SAMPLE_STREAM_ID != 0 {
+            sample_id.stream_id = Some(buf.get_u64_le());
+        }
+        if sample_type & PERF_SAMPLE_CPU != 0 {
+            sample_id.cpu = Some(buf.get_u32_le());
+            buf.advance(4); // reserved
+        }
+        if sample_type & PERF_SAMPLE_IDENTIFIER != 0 {
+            sample_id.id = Some(buf.get_u64_le());
+        }
+
+        sample_id
+    }
+
+    /// Only the trailing block actually exists when the counter was opened
+    /// with `sample_id_all`; callers without it must not consume any bytes.
+    fn parse_if_enabled(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Option<Self> {
+        if sample_id_all {
+            Some(SampleId::parse(buf, sample_type))
+        } else {
+            None
+        }
+    }
+}
+
+/// `PERF_RECORD_NAMESPACES` (since Linux 4.12): the set of namespaces (net,
+/// mnt, pid, ...) a task belongs to, so container-aware tools can map
+/// symbols back to the right mount/pid namespace.
+pub struct NamespacesRecord {
+    pub pid: u32,
+    pub tid: u32,
+    pub namespaces: Vec<NamespaceLink>,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+/// One `(dev, inode)` pair identifying a single namespace inode.
+#[derive(Clone, Copy, Debug)]
+pub struct NamespaceLink {
+    pub dev: u64,
+    pub inode: u64,
+}
+
+impl NamespacesRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        let pid = buf.get_u32_le();
+        let tid = buf.get_u32_le();
+        let nr_namespaces = buf.get_u64_le();
+
+        let mut namespaces = Vec::with_capacity(nr_namespaces as usize);
+        for _ in 0..nr_namespaces {
+            namespaces.push(NamespaceLink {
+                dev: buf.get_u64_le(),
+                inode: buf.get_u64_le(),
+            });
+        }
+
+        NamespacesRecord {
+            pid,
+            tid,
+            namespaces,
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_KSYMBOL` (since Linux 4.18): a dynamic kernel symbol (e.g. a
+/// BPF program or ftrace trampoline) was registered or unregistered.
+pub struct KsymbolRecord {
+    pub addr: u64,
+    pub len: u32,
+    pub ksym_type: u16,
+    /// `PERF_RECORD_KSYMBOL_FLAGS_UNREGISTER` if set, else a register event.
+    pub flags: u16,
+    pub name: String,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl KsymbolRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        let addr = buf.get_u64_le();
+        let len = buf.get_u32_le();
+        let ksym_type = buf.get_u16_le();
+        let flags = buf.get_u16_le();
+        let name = read_cstr(buf);
+
+        KsymbolRecord {
+            addr,
+            len,
+            ksym_type,
+            flags,
+            name,
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_BPF_EVENT` (since Linux 4.18): a BPF program was loaded or
+/// unloaded.
+pub struct BpfEventRecord {
+    /// `enum perf_bpf_event_type`: load vs. unload.
+    pub event_type: u16,
+    pub flags: u16,
+    /// The BPF program's ID, as seen by `bpftool`/`BPF_OBJ_GET_INFO_BY_FD`.
+    pub prog_id: u32,
+    /// The program's SHA sum tag (`BPF_TAG_SIZE` bytes).
+    pub tag: [u8; 8],
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl BpfEventRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        let event_type = buf.get_u16_le();
+        let flags = buf.get_u16_le();
+        let prog_id = buf.get_u32_le();
+
+        let mut tag = [0u8; 8];
+        for byte in tag.iter_mut() {
+            *byte = buf.get_u8();
+        }
+
+        BpfEventRecord {
+            event_type,
+            flags,
+            prog_id,
+            tag,
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_CGROUP` (since Linux 5.7): a cgroup was created, identified
+/// by its kernfs ID and full path.
+pub struct CgroupRecord {
+    pub id: u64,
+    pub path: String,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl CgroupRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        let id = buf.get_u64_le();
+        let path = read_cstr(buf);
+
+        CgroupRecord {
+            id,
+            path,
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// `PERF_RECORD_TEXT_POKE` (since Linux 5.9): self-modifying/patched code
+/// (e.g. `ftrace`, static keys, or alternative patching) overwrote `old_bytes`
+/// at `addr` with `new_bytes`.
+pub struct TextPokeRecord {
+    pub addr: u64,
+    pub old_bytes: Vec<u8>,
+    pub new_bytes: Vec<u8>,
+    /// Present when the counter was opened with `sample_id_all`.
+    pub sample_id: Option<SampleId>,
+}
+
+impl TextPokeRecord {
+    fn parse(buf: &mut BytesMut, sample_type: u64, sample_id_all: bool) -> Self {
+        let addr = buf.get_u64_le();
+        let old_len = buf.get_u16_le();
+        let new_len = buf.get_u16_le();
+        let old_bytes = buf.split_to(old_len as usize).to_vec();
+        let new_bytes = buf.split_to(new_len as usize).to_vec();
+        // The lengths and bytes are padded to 8-byte alignment before the
+        // trailing `sample_id`.
+        let tot = 4 + old_len as usize + new_len as usize;
+        let padding = ((tot + 7) & !7) - tot;
+        let skip = padding.min(buf.len());
+        buf.advance(skip);
+
+        TextPokeRecord {
+            addr,
+            old_bytes,
+            new_bytes,
+            sample_id: SampleId::parse_if_enabled(buf, sample_type, sample_id_all),
+        }
+    }
+}
+
+/// Read a NUL-terminated string out of the front of `buf`, consuming
+/// through the terminator and the zero padding the kernel adds to align the
+/// string to 8 bytes, so a trailing `sample_id` starts where it should.
+fn read_cstr(buf: &mut BytesMut) -> String {
+    let nul = buf
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or_else(|| buf.len());
+    let raw = buf.split_to(nul);
+    let padding = ((nul + 1 + 7) & !7) - nul;
+    let skip = padding.min(buf.len());
+    buf.advance(skip);
+    String::from_utf8_lossy(&raw).into_owned()
+}
 
 /// The mmap values start with a header.
 struct EventHeader {
-    event_type: SampledEventType,
+    /// The raw `perf_event_header.type`. Kept as the wire value (rather than
+    /// decoded into `SampledEventType` here) because the kernel can emit
+    /// type values this crate doesn't have a variant for yet -- e.g.
+    /// `PERF_RECORD_UNTHROTTLE` -- and those still need to reach
+    /// `RecordContents::Unknown` instead of panicking.
+    event_type: u32,
     misc: Metadata,
     size: u16,
 }
@@ -34,7 +726,7 @@ impl<'a> From<&'a perf_event_header> for EventHeader {
     fn from(raw: &perf_event_header) -> Self {
         Self {
             size: raw.size,
-            event_type: SampledEventType::from_u32(raw.type_).unwrap(),
+            event_type: raw.type_,
             misc: Metadata::from(raw.misc),
         }
     }
@@ -67,7 +759,7 @@ struct Metadata {
     ///        PERF_RECORD_SWITCH_CPU_WIDE record is generated, this
     ///        bit indicates that the context switch is away from the
     ///        current process (instead of into the current process).
-    _multipurpose_lol: bool,
+    multipurpose_lol: bool,
     /// This indicates that the content of PERF_SAMPLE_IP points to the actual instruction that
     /// triggered the event.  See also perf_event_attr.precise_ip. (PERF_RECORD_MISC_EXACT_IP)
     _exact_ip: bool,
@@ -76,13 +768,23 @@ struct Metadata {
     _reserved: bool,
 }
 
+impl Metadata {
+    /// The `PERF_RECORD_MISC_SWITCH_OUT` reading of the aliased
+    /// `multipurpose_lol` bit: only meaningful on `PERF_RECORD_SWITCH`/
+    /// `PERF_RECORD_SWITCH_CPU_WIDE` records, where it means the switch is
+    /// away from the current process rather than into it.
+    pub fn switched_out(&self) -> bool {
+        self.multipurpose_lol
+    }
+}
+
 impl From<u16> for Metadata {
     fn from(n: u16) -> Self {
         Self {
             _cpu_mode: CpuMode::from(n),
-            _multipurpose_lol: (n as u32 | PERF_RECORD_MISC_MMAP_DATA) != 0,
-            _exact_ip: (n as u32 | PERF_RECORD_MISC_EXACT_IP) != 0,
-            _reserved: (n as u32 | PERF_RECORD_MISC_EXT_RESERVED) != 0,
+            multipurpose_lol: (n as u32 & PERF_RECORD_MISC_MMAP_DATA) != 0,
+            _exact_ip: (n as u32 & PERF_RECORD_MISC_EXACT_IP) != 0,
+            _reserved: (n as u32 & PERF_RECORD_MISC_EXT_RESERVED) != 0,
         }
     }
 }
@@ -105,7 +807,7 @@ enum CpuMode {
 
 impl From<u16> for CpuMode {
     fn from(n: u16) -> Self {
-        match n as u32 | PERF_RECORD_MISC_CPUMODE_MASK {
+        match n as u32 & PERF_RECORD_MISC_CPUMODE_MASK {
             PERF_RECORD_MISC_CPUMODE_UNKNOWN => CpuMode::Unknown,
             PERF_RECORD_MISC_KERNEL => CpuMode::Kernel,
             PERF_RECORD_MISC_USER => CpuMode::User,
@@ -136,6 +838,11 @@ pub enum SampledEventType {
     LostSamples = PERF_RECORD_LOST_SAMPLES,      //(since Linux 4.2)
     Switch = PERF_RECORD_SWITCH,                 //(since Linux 4.3)
     SwitchCpuWide = PERF_RECORD_SWITCH_CPU_WIDE, //(since Linux 4.3)
+    Namespaces = PERF_RECORD_NAMESPACES,         //(since Linux 4.12)
+    Ksymbol = PERF_RECORD_KSYMBOL,               //(since Linux 4.18)
+    BpfEvent = PERF_RECORD_BPF_EVENT,            //(since Linux 4.18)
+    Cgroup = PERF_RECORD_CGROUP,                 //(since Linux 5.7)
+    TextPoke = PERF_RECORD_TEXT_POKE,            //(since Linux 5.9)
 }
 }
 
@@ -681,3 +1388,161 @@ pub enum SampledEventType {
 //               next_prev_tid
 //                      The thread ID of the previous (if switching in) or
 //                      next (if switching out) thread on the CPU.
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::BufMut;
+
+    #[test]
+    fn sample_id_field_order() {
+        // ABI order: TID, TIME, ID, STREAM_ID, CPU, IDENTIFIER (where
+        // IDENTIFIER's `u64` overrides ID rather than following it).
+        let sample_type = PERF_SAMPLE_TID
+            | PERF_SAMPLE_TIME
+            | PERF_SAMPLE_ID
+            | PERF_SAMPLE_STREAM_ID
+            | PERF_SAMPLE_CPU
+            | PERF_SAMPLE_IDENTIFIER;
+
+        let mut buf = BytesMut::new();
+        buf.put_u32_le(111); // pid
+        buf.put_u32_le(222); // tid
+        buf.put_u64_le(333); // time
+        buf.put_u64_le(444); // id (overridden below by identifier)
+        buf.put_u64_le(555); // stream_id
+        buf.put_u32_le(6); // cpu
+        buf.put_u32_le(0); // cpu reserved
+        buf.put_u64_le(777); // identifier, re-read into `id`
+
+        let sample_id = SampleId::parse(&mut buf, sample_type);
+
+        assert_eq!(sample_id.pid, Some(111));
+        assert_eq!(sample_id.tid, Some(222));
+        assert_eq!(sample_id.time, Some(333));
+        assert_eq!(sample_id.stream_id, Some(555));
+        assert_eq!(sample_id.cpu, Some(6));
+        assert_eq!(sample_id.id, Some(777));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn sample_id_not_present_consumes_nothing() {
+        let mut buf = BytesMut::new();
+        buf.put_u64_le(0xdead_beef);
+        let len_before = buf.len();
+
+        let sample_id = SampleId::parse_if_enabled(&mut buf, 0, false);
+
+        assert!(sample_id.is_none());
+        assert_eq!(buf.len(), len_before);
+    }
+
+    #[test]
+    fn branch_entry_decodes_flags_and_cycles() {
+        let mut buf = BytesMut::new();
+        buf.put_u64_le(0x1000); // from
+        buf.put_u64_le(0x2000); // to
+        // bit 0: mispred, bit 2: in_tx, bits 4..20: cycles = 42
+        let flags = 0b1 | (1 << 2) | (42 << 4);
+        buf.put_u64_le(flags);
+
+        let entry = BranchEntry::parse(&mut buf);
+
+        assert_eq!(entry.from, 0x1000);
+        assert_eq!(entry.to, 0x2000);
+        assert!(entry.mispred);
+        assert!(!entry.predicted);
+        assert!(entry.in_tx);
+        assert!(!entry.abort);
+        assert_eq!(entry.cycles, 42);
+    }
+
+    #[test]
+    fn ksymbol_record_parses_body_and_name() {
+        let mut buf = BytesMut::new();
+        buf.put_u64_le(0xffff_0000); // addr
+        buf.put_u32_le(64); // len
+        buf.put_u16_le(1); // ksym_type
+        buf.put_u16_le(0); // flags
+        buf.put_slice(b"my_ksym\0");
+
+        let record = KsymbolRecord::parse(&mut buf, 0, false);
+
+        assert_eq!(record.addr, 0xffff_0000);
+        assert_eq!(record.len, 64);
+        assert_eq!(record.ksym_type, 1);
+        assert_eq!(record.flags, 0);
+        assert_eq!(record.name, "my_ksym");
+        assert!(record.sample_id.is_none());
+    }
+
+    #[test]
+    fn bpf_event_record_parses_tag() {
+        let mut buf = BytesMut::new();
+        buf.put_u16_le(1); // event_type
+        buf.put_u16_le(0); // flags
+        buf.put_u32_le(999); // prog_id
+        buf.put_slice(&[1, 2, 3, 4, 5, 6, 7, 8]); // tag
+
+        let record = BpfEventRecord::parse(&mut buf, 0, false);
+
+        assert_eq!(record.event_type, 1);
+        assert_eq!(record.prog_id, 999);
+        assert_eq!(record.tag, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn text_poke_record_parses_old_and_new_bytes() {
+        let mut buf = BytesMut::new();
+        buf.put_u64_le(0x4000); // addr
+        buf.put_u16_le(2); // old_len
+        buf.put_u16_le(3); // new_len
+        buf.put_slice(&[0xaa, 0xbb]); // old_bytes
+        buf.put_slice(&[0x01, 0x02, 0x03]); // new_bytes
+
+        let record = TextPokeRecord::parse(&mut buf, 0, false);
+
+        assert_eq!(record.addr, 0x4000);
+        assert_eq!(record.old_bytes, vec![0xaa, 0xbb]);
+        assert_eq!(record.new_bytes, vec![0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn sample_record_skips_fields_before_branch_stack() {
+        // CPU and PERIOD routinely accompany branch-stack sampling; make
+        // sure they (and the other ABI fields between TIME and
+        // BRANCH_STACK) get consumed rather than mistaken for `bnr`.
+        let sample_type = PERF_SAMPLE_IP
+            | PERF_SAMPLE_TIME
+            | PERF_SAMPLE_ADDR
+            | PERF_SAMPLE_ID
+            | PERF_SAMPLE_STREAM_ID
+            | PERF_SAMPLE_CPU
+            | PERF_SAMPLE_PERIOD
+            | PERF_SAMPLE_BRANCH_STACK;
+
+        let mut buf = BytesMut::new();
+        buf.put_u64_le(0x1234); // ip
+        buf.put_u64_le(0x5678); // time
+        buf.put_u64_le(0); // addr
+        buf.put_u64_le(0); // id
+        buf.put_u64_le(0); // stream_id
+        buf.put_u32_le(0); // cpu
+        buf.put_u32_le(0); // cpu reserved
+        buf.put_u64_le(0); // period
+        buf.put_u64_le(1); // bnr
+        buf.put_u64_le(0xa); // from
+        buf.put_u64_le(0xb); // to
+        buf.put_u64_le(0); // flags
+
+        let sample = SampleRecord::parse(&mut buf, sample_type);
+
+        assert_eq!(sample.ip, Some(0x1234));
+        assert_eq!(sample.time, Some(0x5678));
+        let branches = sample.branch_stack.unwrap();
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].from, 0xa);
+        assert_eq!(branches[0].to, 0xb);
+    }
+}
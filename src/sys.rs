@@ -0,0 +1,69 @@
+//! Thin wrappers around the raw syscalls and ioctls this crate needs:
+//! `perf_event_open(2)` itself isn't wrapped by `libc`, so we go through
+//! `libc::syscall` directly, and the `PERF_EVENT_IOC_*` requests go through
+//! `nix`'s `ioctl!` machinery.
+
+use std::io;
+use std::os::unix::io::RawFd;
+
+use libc::{c_int, c_ulong, pid_t};
+
+use raw::perf_event_attr;
+
+#[cfg(target_arch = "x86_64")]
+const SYS_PERF_EVENT_OPEN: libc::c_long = 298;
+#[cfg(target_arch = "aarch64")]
+const SYS_PERF_EVENT_OPEN: libc::c_long = 241;
+
+#[derive(Debug, Fail)]
+pub enum OpenError {
+    #[fail(display = "perf_event_open failed: {}", inner)]
+    Syscall { inner: io::Error },
+}
+
+/// Open a perf event, returning its file descriptor.
+///
+/// `group_fd` should be `-1` to create a new, independent event (or a group
+/// leader); pass an existing leader's fd to join its group.
+pub fn perf_event_open(
+    attr: &perf_event_attr,
+    pid: pid_t,
+    cpu: c_int,
+    group_fd: c_int,
+    flags: c_ulong,
+) -> Result<RawFd, OpenError> {
+    let ret = unsafe {
+        libc::syscall(
+            SYS_PERF_EVENT_OPEN,
+            attr as *const perf_event_attr,
+            pid,
+            cpu,
+            group_fd,
+            flags,
+        )
+    };
+
+    if ret < 0 {
+        Err(OpenError::Syscall {
+            inner: io::Error::last_os_error(),
+        })
+    } else {
+        Ok(ret as RawFd)
+    }
+}
+
+/// Passed as the `ioctl` argument to `PERF_EVENT_IOC_ENABLE`/`_DISABLE`/
+/// `_RESET` to apply the operation to an entire event group instead of just
+/// the fd it's issued against.
+pub const PERF_IOC_FLAG_GROUP: c_int = 1;
+
+// PERF_EVENT_IOC_ENABLE/DISABLE/REFRESH/RESET are `_IO('$', N)` -- no
+// direction or size bits -- not `_IOW`, even though they take an `arg`
+// (the PERF_IOC_FLAG_GROUP flag, or the refresh count). `ioctl_write_int!`
+// would encode the `_IOW` form and the kernel's `switch (cmd)` wouldn't
+// recognize the request, so build the bare `_IO` request code with
+// `request_code_none!` and use `ioctl_write_int_bad!` to still pass `arg`.
+ioctl_write_int_bad!(enable, request_code_none!(b'$', 0));
+ioctl_write_int_bad!(disable, request_code_none!(b'$', 1));
+ioctl_write_int_bad!(refresh, request_code_none!(b'$', 2));
+ioctl_write_int_bad!(reset, request_code_none!(b'$', 3));